@@ -1,35 +1,418 @@
 #!/usr/bin/env python3
+import asyncio
+import hashlib
+import ipaddress
 import os
 import random
 import re
+import secrets
+import socket
+import sys
+import time
+import unicodedata
+import uuid
+from collections.abc import Awaitable, Callable
 from contextlib import asynccontextmanager
+from difflib import SequenceMatcher
+from typing import Annotated, Literal, NamedTuple
+from urllib.parse import urljoin, urlparse
 
 import anitopy
 import httpx
-from fastapi import FastAPI, HTTPException
-from fastapi.responses import RedirectResponse
+from fastapi import Body, FastAPI, Header, HTTPException, Query, Request
+from fastapi.exceptions import RequestValidationError
+from fastapi.middleware.cors import CORSMiddleware
+from fastapi.middleware.gzip import GZipMiddleware
+from fastapi.responses import (
+    JSONResponse,
+    RedirectResponse,
+    Response,
+    StreamingResponse,
+)
 from fastapi_cache import FastAPICache
 from fastapi_cache.backends.inmemory import InMemoryBackend
-from fastapi_cache.decorator import cache
+from fastapi_cache.backends.redis import RedisBackend
 from loguru import logger
 from lxml import html
+from pydantic import BaseModel
+from prometheus_client import CONTENT_TYPE_LATEST, generate_latest
+from redis import asyncio as aioredis
+from starlette.exceptions import HTTPException as StarletteHTTPException
 
-from mona.tvdb import TVDB
+from mona import artwork
+from mona.cache import cache, cache_stats, invalidate, invalidate_all
+from mona.client import HTTP_TIMEOUT_SECS, describe_proxy, http_client
+from mona.metrics import record_request
+from mona.tvdb import TVDB, SearchResult, TVDBError, TVDBRateLimitError
+
+
+# every line carries the id of the request that logged it
+logger.configure(extra={"request_id": "-"})
+logger.remove()
+logger.add(
+    sys.stderr,
+    format=(
+        "<green>{time:YYYY-MM-DD HH:mm:ss.SSS}</green> | <level>{level: <8}</level> | "
+        "{extra[request_id]} | <cyan>{name}</cyan>:<cyan>{function}</cyan> - "
+        "<level>{message}</level>"
+    ),
+)
+
+
+class ApiError(BaseModel):
+    code: str
+    message: str
+    source: str | None = None
 
 
 @asynccontextmanager
 async def lifespan(app: FastAPI):
-    FastAPICache.init(InMemoryBackend())
+    # shared by every replica when set, so they don't each spend tvdb quota
+    if redis_url := os.environ.get("REDIS_URL"):
+        FastAPICache.init(RedisBackend(aioredis.from_url(redis_url)), prefix="mona")
+    else:
+        FastAPICache.init(InMemoryBackend(), prefix="mona")
+    if proxy := describe_proxy():
+        logger.info(f"Sending upstream requests through proxy {proxy}")
+    else:
+        logger.info("No outbound proxy configured")
     await tvdb.login()
     yield
+    await http_client.aclose()
+
+
+app = FastAPI(
+    docs_url="/",
+    redoc_url=None,
+    lifespan=lifespan,
+    responses={"default": {"model": ApiError, "description": "Error"}},
+)
+
+tvdb = TVDB(os.environ["TVDB_API_KEY"], os.environ.get("TVDB_PIN"), http_client)
+
+
+# scripts branch on the code, the message is for people
+ERROR_CODES = {
+    400: "invalid_request",
+    401: "unauthorized",
+    403: "forbidden",
+    404: "not_found",
+    405: "method_not_allowed",
+    422: "invalid_request",
+    429: "rate_limited",
+    502: "upstream_error",
+    503: "unavailable",
+    504: "upstream_timeout",
+}
+
+
+def error_response(
+    status_code: int,
+    message: str,
+    source: str | None = None,
+    headers: dict[str, str] | None = None,
+) -> JSONResponse:
+    error = ApiError(
+        code=ERROR_CODES.get(status_code, "error"), message=message, source=source
+    )
+    return JSONResponse(
+        status_code=status_code, content=error.model_dump(), headers=headers
+    )
+
+
+@app.exception_handler(StarletteHTTPException)
+async def http_error_handler(request: Request, exc: StarletteHTTPException):
+    return error_response(exc.status_code, str(exc.detail), headers=exc.headers)
+
+
+@app.exception_handler(RequestValidationError)
+async def validation_error_handler(request: Request, exc: RequestValidationError):
+    # e.g. "query.limit: Input should be a valid integer"
+    message = "; ".join(
+        f"{'.'.join(str(x) for x in error['loc'])}: {error['msg']}"
+        for error in exc.errors()
+    )
+    return error_response(422, message or "request is invalid")
+
+
+@app.exception_handler(TVDBError)
+async def tvdb_error_handler(request: Request, exc: TVDBError):
+    if isinstance(exc, TVDBRateLimitError):
+        status_code = 429
+    elif isinstance(exc.__cause__, httpx.TimeoutException):
+        status_code = 504
+    else:
+        status_code = 502
+    return error_response(status_code, str(exc), source="tvdb")
+
+
+@app.exception_handler(httpx.TimeoutException)
+async def timeout_error_handler(request: Request, exc: httpx.TimeoutException):
+    return error_response(504, "upstream timed out", source=exc.request.url.host)
+
+
+def env_flag(name: str, default: bool) -> bool:
+    return os.environ.get(name, str(default)).lower() in ("1", "true", "yes")
+
+
+UPGRADE_HTTP_IMAGES = env_flag("UPGRADE_HTTP_IMAGES", True)
+MAX_QUERY_LENGTH = int(os.environ.get("MAX_QUERY_LENGTH", "512"))
+FANART_TV_API_KEY = os.environ.get("FANART_TV_API_KEY")
+TMDB_API_KEY = os.environ.get("TMDB_API_KEY")
+DEFAULT_ART_LANG = os.environ.get("DEFAULT_ART_LANG", "eng")
+ARTWORK_QUALITY = os.environ.get("ARTWORK_QUALITY", "default")
+SeasonType = Literal["official", "dvd", "absolute", "alternate", "regional", "altdvd"]
+SEASON_TYPE = os.environ.get("SEASON_TYPE", "official")
+SEASON_ART_FALLBACK_PREVIOUS = env_flag("SEASON_ART_FALLBACK_PREVIOUS", False)
+DISABLE_SUBSPLEASE = env_flag("DISABLE_SUBSPLEASE", False)
+ADMIN_TOKEN = os.environ.get("ADMIN_TOKEN")
+# uploaders sometimes edit descriptions, so torrent art can have its own ttl
+TORRENT_CACHE_TTL_SECS = int(os.environ.get("TORRENT_CACHE_TTL_SECS", "86400"))
+MAX_FANART_COUNT = 20
+MAX_SEARCH_RESULTS = 20
+MAX_PROXY_BYTES = 20 * 1024 * 1024
+MAX_PROXY_REDIRECTS = 3
+MAX_IMAGE_PROBES = 5
+TORRENT_ALLOWED_HOSTS = {
+    host.strip().lower()
+    for host in os.environ.get(
+        "TORRENT_ALLOWED_HOSTS", "nyaa.si,sukebei.nyaa.si"
+    ).split(",")
+    if host.strip()
+}
+# hosts /validate-image will check, subdomains included, "*" allows any public host
+IMAGE_ALLOWED_HOSTS = {
+    host.strip().lower()
+    for host in os.environ.get(
+        "IMAGE_ALLOWED_HOSTS",
+        "artworks.thetvdb.com,subsplease.org,media.kitsu.app,media.kitsu.io,"
+        "image.tmdb.org,assets.fanart.tv,i.imgur.com,files.catbox.moe",
+    ).split(",")
+    if host.strip()
+}
+REDIRECT_MAX_AGE_SECS = int(os.environ.get("REDIRECT_MAX_AGE_SECS", "86400"))
+MAX_INFLIGHT = int(os.environ.get("MAX_INFLIGHT", "256"))
+RATE_LIMIT_RPM = int(os.environ.get("RATE_LIMIT_RPM", "0"))
+# fly-client-ip can only be believed when fly's edge is the one setting it
+TRUST_PROXY_HEADER = env_flag("TRUST_PROXY_HEADER", "FLY_APP_NAME" in os.environ)
+MAX_BATCH_SIZE = int(os.environ.get("MAX_BATCH_SIZE", "50"))
+batch_semaphore = asyncio.Semaphore(int(os.environ.get("BATCH_CONCURRENCY", "8")))
+SEASON_ART_TYPE = {"banner": artwork.SEASON_BANNER, "poster": artwork.SEASON_POSTER}[
+    os.environ.get("SEASON_ART_TYPE", "poster")
+]
+
+
+def upgrade_url(url: str) -> str:
+    # browsers block http images on https pages
+    if UPGRADE_HTTP_IMAGES and url.startswith("http://"):
+        return f"https://{url.removeprefix('http://')}"
+    return url
+
+
+ResponseFormat = Literal["redirect", "json"]
+
+
+async def art_response(
+    url: str, format: ResponseFormat = "redirect", proxy: bool = False
+) -> Response:
+    url = upgrade_url(url)
+    if proxy:
+        return await proxy_image(url)
+    if format == "json":
+        return JSONResponse({"url": url})
+    return RedirectResponse(url=url, status_code=302)
+
+
+async def proxy_image(url: str) -> Response:
+    # torrent descriptions are user-controlled, so every hop is checked before
+    # it's fetched, a public url could otherwise redirect somewhere internal
+    for _ in range(MAX_PROXY_REDIRECTS + 1):
+        if not await is_public_https_url(url):
+            raise HTTPException(status_code=502, detail="image can't be proxied")
+        try:
+            upstream = await http_client.send(
+                http_client.build_request("GET", url), stream=True
+            )
+        except httpx.HTTPError as e:
+            logger.warning(f"Proxy fetch of {url} failed: {e}")
+            raise HTTPException(status_code=502, detail="image fetch failed")
+        if not upstream.is_redirect:
+            break
+        await upstream.aclose()
+        url = urljoin(url, upstream.headers.get("location", ""))
+    else:
+        raise HTTPException(status_code=502, detail="too many redirects")
+    length = int(upstream.headers.get("content-length") or 0)
+    if upstream.status_code != 200 or length > MAX_PROXY_BYTES:
+        await upstream.aclose()
+        raise HTTPException(status_code=502, detail="image fetch failed")
+    # anything else, like html, would be served from our own origin
+    content_type = upstream.headers.get("content-type", "")
+    if not content_type.startswith("image/"):
+        await upstream.aclose()
+        raise HTTPException(status_code=502, detail="upstream isn't an image")
 
+    async def body():
+        size = 0
+        try:
+            async for chunk in upstream.aiter_bytes():
+                size += len(chunk)
+                if size > MAX_PROXY_BYTES:
+                    logger.warning(f"Proxied image {url} exceeded size cap")
+                    break
+                yield chunk
+        finally:
+            await upstream.aclose()
+
+    return StreamingResponse(
+        body(),
+        media_type=content_type,
+        headers={"X-Content-Type-Options": "nosniff"},
+    )
+
+
+def tvdb_thumbnail(url: str) -> str:
+    # tvdb serves a thumbnail next to each artwork, suffixed with _t
+    if urlparse(url).hostname != "artworks.thetvdb.com":
+        return url
+    root, dot, ext = url.rpartition(".")
+    if not dot or "/" in ext or root.endswith("_t"):
+        return url
+    return f"{root}_t.{ext}"
+
+
+# all middleware lives here, registered innermost first since the last one added
+# wraps the rest
+# small bodies like the healthcheck and redirects are left as they are
+app.add_middleware(GZipMiddleware, minimum_size=500)
+
+
+@app.middleware("http")
+async def etag_middleware(request: Request, call_next):
+    response = await call_next(request)
+    if not (location := response.headers.get("location")):
+        return response
+    source = response.headers.get("x-source", "")
+    etag = f'"{hashlib.sha1(f"{source}:{location}".encode()).hexdigest()}"'
+    if_none_match = request.headers.get("if-none-match", "")
+    # the art behind a redirect lives as long as the cache entry that produced it,
+    # unless the endpoint said otherwise, like unseeded /fanart's no-cache
+    cache_control = response.headers.get(
+        "cache-control", f"public, max-age={REDIRECT_MAX_AGE_SECS}"
+    )
+    if etag in (x.strip() for x in if_none_match.split(",")):
+        return Response(
+            status_code=304, headers={"ETag": etag, "Cache-Control": cache_control}
+        )
+    response.headers["ETag"] = etag
+    response.headers["Cache-Control"] = cache_control
+    return response
+
+
+# request counts per client ip for the current minute
+rate_limit_window = 0
+rate_limit_counts: dict[str, int] = {}
+
+
+def client_ip(request: Request) -> str:
+    # fly's edge sets this itself, unlike x-forwarded-for whose first entry is
+    # whatever the client sent. anywhere else the client could rotate it freely
+    if TRUST_PROXY_HEADER and (fly_client_ip := request.headers.get("fly-client-ip")):
+        return fly_client_ip
+    return request.client.host if request.client else "unknown"
+
+
+def charge_rate_limit(request: Request, cost: int = 1) -> int | None:
+    # returns the seconds until the window resets once the client is over
+    global rate_limit_window
+    if not RATE_LIMIT_RPM:
+        return None
+    now = time.time()
+    if (window := int(now // 60)) != rate_limit_window:
+        rate_limit_window = window
+        rate_limit_counts.clear()
+    ip = client_ip(request)
+    rate_limit_counts[ip] = rate_limit_counts.get(ip, 0) + cost
+    if rate_limit_counts[ip] > RATE_LIMIT_RPM:
+        return 60 - int(now % 60)
+    return None
+
+
+def rate_limit_response(retry_after: int) -> JSONResponse:
+    return error_response(
+        429, "rate limit exceeded", headers={"Retry-After": str(retry_after)}
+    )
 
-app = FastAPI(docs_url="/", redoc_url=None, lifespan=lifespan)
 
-tvdb = TVDB(os.environ["TVDB_API_KEY"])
+@app.middleware("http")
+async def rate_limit_middleware(request: Request, call_next):
+    if request.url.path == "/healthcheck":
+        return await call_next(request)
+    if retry_after := charge_rate_limit(request):
+        return rate_limit_response(retry_after)
+    return await call_next(request)
+
+
+inflight = 0
+
+
+@app.middleware("http")
+async def concurrency_limit_middleware(request: Request, call_next):
+    global inflight
+    # probes must keep passing while we shed load
+    if request.url.path == "/healthcheck":
+        return await call_next(request)
+    if inflight >= MAX_INFLIGHT:
+        logger.warning(f"Rejecting {request.url.path}, {inflight} requests in flight")
+        return error_response(503, "too many requests in flight")
+    inflight += 1
+    try:
+        return await call_next(request)
+    finally:
+        inflight -= 1
+
+
+@app.middleware("http")
+async def metrics_middleware(request: Request, call_next):
+    # outside the limiters, so their 429s and 503s are counted too
+    start = time.monotonic()
+    response = await call_next(request)
+    # the route template, so ids and queries don't each get their own series
+    route = getattr(request.scope.get("route"), "path", "unmatched")
+    record_request(
+        request.method, route, response.status_code, time.monotonic() - start
+    )
+    return response
+
+
+@app.middleware("http")
+async def request_id_middleware(request: Request, call_next):
+    # registered after the others so it wraps them, a proxy in front may have set one
+    request_id = request.headers.get("x-request-id") or uuid.uuid4().hex
+    with logger.contextualize(request_id=request_id):
+        response = await call_next(request)
+    response.headers["X-Request-Id"] = request_id
+    return response
+
+
+# browser frontends fetch the json modes directly. added last so it's outermost,
+# and 429s and 503s carry the headers a browser needs to read them
+app.add_middleware(
+    CORSMiddleware,
+    allow_origins=os.environ.get("CORS_ALLOW_ORIGIN", "*").split(","),
+    allow_methods=["GET", "HEAD", "POST"],
+    expose_headers=["X-Source", "X-Fanart-Fallback", "X-Request-Id", "ETag"],
+)
+
+
+def fold_accents(text: str) -> str:
+    decomposed = unicodedata.normalize("NFKD", text)
+    return "".join(c for c in decomposed if not unicodedata.combining(c))
 
 
 def slugify(text: str) -> str:
+    # fold accents and macrons, "Tōkyō" becomes "tokyo" like subsplease's urls
+    text = fold_accents(text)
     # lowercase
     text = text.lower()
     # strip bbcode
@@ -47,6 +430,28 @@ def slugify(text: str) -> str:
     return text
 
 
+def parse_query(query: str) -> dict[str, str]:
+    if len(query) > MAX_QUERY_LENGTH:
+        raise HTTPException(status_code=400, detail="query is too long")
+    try:
+        parsed = anitopy.parse(" ".join(query.split()))
+    except Exception as e:
+        logger.warning(f"Failed to parse query {query!r}: {e}")
+        parsed = None
+    if not parsed:
+        raise HTTPException(status_code=400, detail="query is invalid")
+    return parsed
+
+
+def lookup_query(query: str) -> dict[str, str]:
+    # every lookup is case-insensitive, so variants share their cache entries.
+    # all of it, the file name and release group end up in keys too
+    return {
+        k: v.lower() if isinstance(v, str) else [x.lower() for x in v]
+        for k, v in parse_query(query).items()
+    }
+
+
 def get_search_string(parsed: dict[str, str]) -> str | None:
     if not (search_string := parsed.get("anime_title")):
         return None
@@ -55,153 +460,1166 @@ def get_search_string(parsed: dict[str, str]) -> str | None:
     return search_string
 
 
-async def get_season_image(tvdb_id: int, season_number: str | list[str]) -> str | None:
-    if not season_number or not tvdb_id:
+@cache(expire=86400)
+async def get_season_image(
+    tvdb_id: int,
+    season_number: int,
+    lang: str | None = None,
+    season_type: str = SEASON_TYPE,
+) -> str | None:
+    if not tvdb_id:
         return None
-    season_number = (
-        season_number if isinstance(season_number, str) else season_number[0]
-    )
     series = await tvdb.get_series_extended(tvdb_id)
     if not series or not (seasons := series.get("seasons")):
         return None
-    season = next(
-        (x for x in seasons if x.get("number") == int(season_number)),
-        None,
-    )
-    if season:
-        season_details = await tvdb.get_season_extended(season.get("id"))
-        if not season_details:
-            return None
-        artwork = season_details.get("artwork", [])
-        season_image = next((x for x in artwork if x.get("type") == 7), {}).get("image")
-        return season_image
-    return None
+    seasons = [x for x in seasons if x.get("type", {}).get("type") == season_type]
+    season = next((x for x in seasons if x.get("number") == season_number), None)
+    if season and (image := await get_season_artwork(season.get("id"), lang)):
+        return image
+    if not SEASON_ART_FALLBACK_PREVIOUS:
+        return None
+    # a season mid-release may have no art yet, the last one is a closer stand-in
+    previous = [x for x in seasons if 0 < x.get("number", 0) < season_number]
+    if not previous:
+        return None
+    nearest = max(previous, key=lambda x: x.get("number", 0))
+    return await get_season_artwork(nearest.get("id"), lang)
+
+
+async def get_season_artwork(season_id: int, lang: str | None = None) -> str | None:
+    season_details = await tvdb.get_season_extended(season_id)
+    if not season_details:
+        return None
+    season_artworks = [
+        x
+        for x in season_details.get("artwork") or []
+        if x.get("type") == SEASON_ART_TYPE and x.get("image")
+    ]
+    season_image = next(iter(prefer_language(season_artworks, lang)), {}).get("image")
+    return season_image
+
+
+def prefer_language(artworks: list[dict], lang: str | None) -> list[dict]:
+    if not lang:
+        return artworks
+    # language-neutral art is the next best thing to the requested language
+    return (
+        [x for x in artworks if x.get("language") == lang]
+        or [x for x in artworks if not x.get("language")]
+        or artworks
+    )
 
 
-def priority_sort_key(obj):
-    lang_priority = 0 if obj.get("primary_language") == "jpn" else 1
-    type_priority = 0 if obj.get("type") == "series" else 1
-    return (lang_priority, type_priority)
+def normalize_title(text: str) -> str:
+    # "Re:Zero", "Re Zero" and "ReZero" all compare equal
+    return re.sub(r"[\W_]+", "", fold_accents(text).lower())
+
+
+def title_similarity(title: str, obj: SearchResult) -> float:
+    names = [
+        obj.get("name"),
+        *(obj.get("aliases") or []),
+        *(obj.get("translations") or {}).values(),
+    ]
+    target = normalize_title(title)
+    return max(
+        (
+            SequenceMatcher(None, target, normalize_title(name)).ratio()
+            for name in names
+            if isinstance(name, str)
+        ),
+        default=0.0,
+    )
+
+
+class ScoringConfig(NamedTuple):
+    # the defaults rank language over type over title similarity
+    lang_bonus: float = 100
+    series_bonus: float = 50
+    movie_bonus: float = 25
+    title_weight: float = 10
+    year_bonus: float = 60
+    year_penalty: float = 30
+
+
+SCORING = ScoringConfig(
+    **{
+        name: float(os.environ.get(f"SCORE_{name.upper()}", default))
+        for name, default in ScoringConfig._field_defaults.items()
+    }
+)
+
+
+def candidate_year(obj: SearchResult) -> int | None:
+    year = obj.get("year") or (obj.get("first_air_time") or "")[:4]
+    try:
+        return int(year)
+    except (TypeError, ValueError):
+        return None
 
 
+def match_score(
+    obj: SearchResult,
+    title: str = "",
+    year: int | None = None,
+    scoring: ScoringConfig = SCORING,
+) -> float:
+    score = scoring.lang_bonus if obj.get("primary_language") == "jpn" else 0
+    # lists/collections and other non-title entries get no type bonus
+    score += {"series": scoring.series_bonus, "movie": scoring.movie_bonus}.get(
+        obj.get("type"), 0
+    )
+    # coarse, so near-identical names keep tvdb's relevance order
+    if title:
+        score += round(title_similarity(title, obj), 1) * scoring.title_weight
+    # remakes share a title, the year tells them apart
+    if year and (found := candidate_year(obj)):
+        score += scoring.year_bonus if found == year else 0
+        score -= scoring.year_penalty if abs(found - year) > 1 else 0
+    return score
+
+
+def split_search_string(search_string: str) -> tuple[str, int | None]:
+    # the year get_search_string appended, if any
+    if year_match := re.search(r"\s*\((\d{4})\)$", search_string):
+        return search_string[: year_match.start()], int(year_match.group(1))
+    return search_string, None
+
+
+# shared by the matcher and /search, so listing candidates costs no extra quota
 @cache(expire=86400)
-async def find_best_match(search_string: str) -> dict | None:
-    results = await tvdb.search(search_string)
+async def search_tvdb(search_string: str) -> list[SearchResult]:
+    return await tvdb.search(search_string)
+
+
+@cache(expire=86400)
+async def find_best_match(
+    search_string: str, type: str | None = None
+) -> SearchResult | None:
+    results = await search_tvdb(search_string)
+    if type:
+        results = [x for x in results if x.get("type") == type]
     if not results:
         logger.info(f"No results found for: {search_string}")
         return None
-    selected = sorted(results, key=priority_sort_key)[0]
+    title, year = split_search_string(search_string)
+    selected = max(results, key=lambda x: match_score(x, title, year))
     return selected
 
 
+class PosterOptions(NamedTuple):
+    remote_id: str | None = None
+    text: bool = True
+    lang: str | None = None
+    season_type: str = SEASON_TYPE
+
+
 @cache(expire=86400)
-async def get_tvdb_poster(parsed: dict[str, str]) -> str | None:
-    search_string = get_search_string(parsed)
-    if not search_string:
+async def find_by_remote_id(remote_id: str) -> SearchResult | None:
+    results = await tvdb.search_by_remote_id(remote_id)
+    for result in results:
+        for type in ("series", "movie"):
+            if record := result.get(type):
+                # shaped like a search result so callers don't need to care
+                return SearchResult(
+                    tvdb_id=record.get("id"),
+                    type=type,
+                    name=record.get("name"),
+                    image_url=record.get("image"),
+                )
+    logger.info(f"No results found for remote id: {remote_id}")
+    return None
+
+
+async def resolve_match(
+    parsed: dict[str, str], remote_id: str | None = None
+) -> SearchResult | None:
+    # a cross-database id is exact, so try it before fuzzy title search
+    if remote_id and (series := await find_by_remote_id(remote_id)):
+        return series
+    if not (search_string := get_search_string(parsed)):
         return None
-    series = await find_best_match(search_string)
+    return await find_best_match(search_string)
+
+
+@cache(expire=86400)
+async def get_artworks(
+    tvdb_id: int, type: str, series_art: int, movie_art: int
+) -> list[dict]:
+    if type == "series":
+        result = await tvdb.get_series_artworks(tvdb_id, type=series_art)
+        artworks = (result.get("artworks") or []) if result else []
+        art_type = series_art
+    elif type == "movie":
+        movie = await tvdb.get_movie_extended(tvdb_id)
+        artworks = (movie.get("artworks") or []) if movie else []
+        art_type = movie_art
+    else:
+        return []
+    # skip entries without a url so callers always get a usable one
+    return [x for x in artworks if x.get("type") == art_type and x.get("image")]
+
+
+async def get_match_artworks(
+    parsed: dict[str, str], series_art: int, movie_art: int
+) -> list[dict]:
+    series = await resolve_match(parsed)
+    if not series or not (series_id := series.get("tvdb_id")):
+        return []
+    return await get_artworks(series_id, series.get("type"), series_art, movie_art)
+
+
+def largest_image(artworks: list[dict]) -> str | None:
+    if not (candidates := [x for x in artworks if x.get("image")]):
+        return None
+    largest = max(
+        candidates, key=lambda x: (x.get("width") or 0) * (x.get("height") or 0)
+    )
+    return largest.get("image")
+
+
+# what get_tvdb_poster derives a season from
+SEASON_KEYS = ("anime_season", "episode_number")
+
+
+def first_number(value: str | list[str] | None) -> int | None:
+    # a range like "01-02" parses to a list, the first one is what's shown
+    if isinstance(value, list):
+        value = value[0] if value else None
+    try:
+        return int(value)
+    except (TypeError, ValueError):
+        return None
+
+
+# keyed by series alone, so every episode of a show shares one episode listing
+@cache(expire=86400)
+async def get_absolute_seasons(tvdb_id: int) -> dict[str, int]:
+    seasons: dict[str, set[int]] = {}
+    for episode in await tvdb.get_all_series_episodes(tvdb_id):
+        absolute, season = episode.get("absoluteNumber"), episode.get("seasonNumber")
+        if absolute and season:
+            seasons.setdefault(str(absolute), set()).add(season)
+    # ambiguous numbers are left out, they fall back to the series poster
+    return {k: v.pop() for k, v in seasons.items() if len(v) == 1}
+
+
+async def get_poster_season(series: SearchResult, parsed: dict) -> int | None:
+    if season := parsed.get("anime_season"):
+        return int(season if isinstance(season, str) else season[0])
+    absolute = first_number(parsed.get("episode_number"))
+    # movies have no episode listing to map against
+    if not absolute or series.get("type") != "series":
+        return None
+    season = (await get_absolute_seasons(series.get("tvdb_id"))).get(str(absolute))
+    # plain season 1 episode numbers were never meant as anything but the series
+    return season if season and season >= 2 else None
+
+
+@cache(expire=86400)
+async def get_tvdb_poster(
+    parsed: dict[str, str], options: PosterOptions = PosterOptions()
+) -> str | None:
+    series = await resolve_match(parsed, options.remote_id)
     if not series:
         return None
-    series_image = series.get("image_url")
-    if not (series_id := series.get("tvdb_id")) or not (
-        season := parsed.get("anime_season")
-    ):
-        return series_image
-    season_image = await get_season_image(series_id, season)
-    return season_image or series_image
+    series_id = series.get("tvdb_id")
+    if series_id and any(parsed.get(x) for x in SEASON_KEYS):
+        # a failed season lookup should still fall back to the series poster
+        try:
+            season_number = await get_poster_season(series, parsed)
+            season_image = season_number and await get_season_image(
+                series_id, season_number, options.lang, options.season_type
+            )
+            if season_image:
+                return season_image
+        except (TVDBError, ValueError) as e:
+            logger.warning(f"Season image lookup failed for {series_id}: {e}")
+    return await get_series_poster(series, options.text, options.lang)
+
+
+async def get_series_poster(
+    series: dict, text: bool = True, lang: str | None = None
+) -> str | None:
+    # the search hit's image is free, artworks cost an extra request. it's in no
+    # particular language, so it only stands in when the default one was asked for
+    search_hit = ARTWORK_QUALITY != "max" and lang in (None, DEFAULT_ART_LANG)
+    if not (series_id := series.get("tvdb_id")) or (search_hit and text):
+        return series.get("image_url")
+    artworks = await get_artworks(
+        series_id, series.get("type"), artwork.SERIES_POSTER, artwork.MOVIE_POSTER
+    )
+    artworks = prefer_language(artworks, lang)
+    textless = [x for x in artworks if x.get("includesText") is False]
+    if not text and textless:
+        artworks = textless
+    elif search_hit:
+        return series.get("image_url")
+    return largest_image(artworks) or series.get("image_url")
+
+
+def get_subsplease_slugs(name: str) -> list[str]:
+    # drop trailing words until a show page matches
+    words = slugify(name).split("-")
+    return ["-".join(words[:i]) for i in range(len(words), -1, -1)]
 
 
 @cache(expire=86400)
 async def get_subsplease_poster(name: str) -> str | None:
     logger.info(f"Searching for: {name}")
-    words = slugify(name).split("-")
-    async with httpx.AsyncClient(http2=True) as client:
-        for _ in range(len(words) + 1):
-            url = f"https://subsplease.org/shows/{'-'.join(words)}"
-            response = await client.get(url, follow_redirects=True)
-            if response.status_code == 200:
-                img_src = html.fromstring(response.text).xpath("//img/@src")
-                if img_src:
-                    return f"https://subsplease.org{img_src[0]}"
-            words = words[:-1]
+    for slug in get_subsplease_slugs(name):
+        url = f"https://subsplease.org/shows/{slug}"
+        response = await http_client.get(url, follow_redirects=True)
+        if response.status_code == 200:
+            page = html.fromstring(response.text)
+            # the first img on the page is the site logo, not the cover
+            img_src = page.xpath(
+                "//div[contains(@class, 'entry-content')]//img/@src"
+            ) or page.xpath("//img[contains(@src, '/wp-content/uploads/')]/@src")
+            if img_src:
+                return urljoin(str(response.url), img_src[0])
+    return None
+
+
+@cache(expire=86400)
+async def get_kitsu_poster(name: str) -> str | None:
+    response = await http_client.get(
+        "https://kitsu.io/api/edge/anime",
+        params={"filter[text]": name, "page[limit]": 1},
+        follow_redirects=True,
+    )
+    if response.status_code != 200:
+        return None
+    results = response.json().get("data") or []
+    if not results:
+        return None
+    attributes = results[0].get("attributes") or {}
+    return (attributes.get("posterImage") or {}).get("original")
+
+
+@cache(expire=86400)
+async def get_tmdb_poster(title: str, year: str | None = None) -> str | None:
+    params = {"api_key": TMDB_API_KEY, "query": title}
+    if year:
+        params["year"] = year
+    response = await http_client.get(
+        "https://api.themoviedb.org/3/search/movie", params=params
+    )
+    if response.status_code != 200:
+        return None
+    # the first page holds the best matches, later ones aren't worth a request
+    results = response.json().get("results") or []
+    paths = [x["poster_path"] for x in results if x.get("poster_path")]
+    return f"https://image.tmdb.org/t/p/original{paths[0]}" if paths else None
+
+
+async def get_tmdb_movie_poster(parsed: dict[str, str], _) -> str | None:
+    # tvdb covers anime films spottily, an episode number means it's not one
+    if parsed.get("episode_number"):
+        return None
+    return await get_tmdb_poster(parsed["anime_title"], parsed.get("anime_year"))
+
+
+PosterSource = Callable[[dict[str, str], PosterOptions], Awaitable[str | None]]
+
+KNOWN_POSTER_SOURCES: dict[str, PosterSource] = {
+    "tvdb": lambda parsed, options: get_tvdb_poster(parsed, options),
+    "tmdb": get_tmdb_movie_poster,
+    "subsplease": lambda parsed, _: get_subsplease_poster(parsed["anime_title"]),
+    "kitsu": lambda parsed, _: get_kitsu_poster(parsed["anime_title"]),
+}
+# tried in order until one returns a poster
+POSTER_SOURCES: dict[str, PosterSource] = {}
+for name in os.environ.get("POSTER_SOURCES", ",".join(KNOWN_POSTER_SOURCES)).split(","):
+    if (name := name.strip()) in KNOWN_POSTER_SOURCES:
+        POSTER_SOURCES[name] = KNOWN_POSTER_SOURCES[name]
+    elif name:
+        logger.warning(f"Ignoring unknown poster source {name!r}")
+if DISABLE_SUBSPLEASE:
+    POSTER_SOURCES.pop("subsplease", None)
+if not TMDB_API_KEY:
+    POSTER_SOURCES.pop("tmdb", None)
+
+# tracks HTTP_TIMEOUT_SECS unless set, so raising one raises the other
+SOURCE_TIMEOUT_SECS = float(os.environ.get("SOURCE_TIMEOUT_SECS", HTTP_TIMEOUT_SECS))
+SOURCE_TIMEOUTS = {
+    name: float(os.environ.get(f"{name.upper()}_TIMEOUT_SECS", SOURCE_TIMEOUT_SECS))
+    for name in POSTER_SOURCES
+}
+
+
+def poster_source_order(parsed: dict[str, str]) -> list[str]:
+    order = list(POSTER_SOURCES)
+    # subsplease releases are named after their show page, so it's the surest match
+    if str(parsed.get("release_group", "")).lower() == "subsplease" and (
+        "subsplease" in order
+    ):
+        order.remove("subsplease")
+        order.insert(0, "subsplease")
+    return order
+
+
+async def resolve_poster(
+    parsed: dict[str, str], options: PosterOptions, source: str | None = None
+) -> tuple[str, str] | None:
+    for name in [source] if source else poster_source_order(parsed):
+        try:
+            poster = await asyncio.wait_for(
+                POSTER_SOURCES[name](parsed, options), SOURCE_TIMEOUTS[name]
+            )
+        except asyncio.TimeoutError:
+            logger.warning(f"Poster source {name} timed out")
+            continue
+        except (httpx.HTTPError, TVDBError) as e:
+            logger.warning(f"Poster source {name} failed: {e}")
+            continue
+        except Exception:
+            # one broken source shouldn't take the others down with it
+            logger.exception(f"Poster source {name} raised")
+            continue
+        if poster:
+            return name, poster
     return None
 
 
 @app.get("/poster")
-async def poster(query: str):
-    if not (parsed := anitopy.parse(query)) or not (title := parsed.get("anime_title")):
+async def poster(
+    query: str,
+    # an imdb id or a bare numeric one, it ends up in the tvdb url path
+    remote_id: Annotated[str | None, Query(pattern=r"^(tt\d+|\d+)$")] = None,
+    source: str | None = None,
+    prefer: Literal["season", "series"] = "season",
+    size: Literal["thumb", "original"] = "original",
+    text: bool = True,
+    lang: str = DEFAULT_ART_LANG,
+    format: ResponseFormat = "redirect",
+    proxy: bool = False,
+    season_type: SeasonType = SEASON_TYPE,
+):
+    parsed = lookup_query(query)
+    if not parsed.get("anime_title"):
         raise HTTPException(status_code=400, detail="query is invalid")
-    poster = await get_tvdb_poster(parsed)
-    if poster:
-        return RedirectResponse(url=poster, status_code=302)
-    poster = await get_subsplease_poster(title)
-    if poster:
-        return RedirectResponse(url=poster, status_code=302)
+    if prefer == "series":
+        parsed = {k: v for k, v in parsed.items() if k not in SEASON_KEYS}
+    if source and source not in POSTER_SOURCES:
+        raise HTTPException(status_code=400, detail="unknown source")
+    options = PosterOptions(
+        remote_id=remote_id, text=text, lang=lang or None, season_type=season_type
+    )
+    if found := await resolve_poster(parsed, options, source):
+        name, poster = found
+        url = tvdb_thumbnail(poster) if size == "thumb" else poster
+        response = await art_response(url, format, proxy)
+        response.headers["X-Source"] = name
+        return response
     raise HTTPException(status_code=404, detail="poster not found")
 
 
+@app.post("/poster/batch")
+async def poster_batch(request: Request, queries: Annotated[list[str], Body()]):
+    if len(queries) > MAX_BATCH_SIZE:
+        raise HTTPException(
+            status_code=400, detail=f"at most {MAX_BATCH_SIZE} queries per request"
+        )
+    # the middleware already counted the request itself as one
+    if retry_after := charge_rate_limit(request, max(len(queries) - 1, 0)):
+        return rate_limit_response(retry_after)
+
+    async def resolve(query: str) -> dict:
+        async with batch_semaphore:
+            try:
+                parsed = lookup_query(query)
+                if not parsed.get("anime_title"):
+                    return {"query": query, "url": None, "status": 400}
+                found = await resolve_poster(
+                    parsed, PosterOptions(lang=DEFAULT_ART_LANG or None)
+                )
+            except HTTPException as e:
+                return {"query": query, "url": None, "status": e.status_code}
+            except TVDBError as e:
+                logger.warning(f"Poster lookup failed for {query!r}: {e}")
+                return {"query": query, "url": None, "status": 502}
+        if not found:
+            return {"query": query, "url": None, "status": 404}
+        return {"query": query, "url": upgrade_url(found[1]), "status": 200}
+
+    return await asyncio.gather(*(resolve(x) for x in queries))
+
+
+@app.get("/movie-poster")
+async def movie_poster(
+    query: str,
+    lang: str = DEFAULT_ART_LANG,
+    format: ResponseFormat = "redirect",
+    proxy: bool = False,
+):
+    parsed = lookup_query(query)
+    if not (search_string := get_search_string(parsed)):
+        raise HTTPException(status_code=400, detail="query is invalid")
+    # for when the film is wanted even though a series shares its title
+    movie = await find_best_match(search_string, "movie")
+    if not movie or not (movie_id := movie.get("tvdb_id")):
+        raise HTTPException(status_code=404, detail="movie not found")
+    artworks = prefer_language(
+        await get_artworks(
+            movie_id, "movie", artwork.SERIES_POSTER, artwork.MOVIE_POSTER
+        ),
+        lang or None,
+    )
+    if poster := largest_image(artworks) or movie.get("image_url"):
+        return await art_response(poster, format, proxy)
+    raise HTTPException(status_code=404, detail="poster not found")
+
+
+@app.get("/search")
+async def search(query: str, limit: int = 10):
+    parsed = lookup_query(query)
+    if not (search_string := get_search_string(parsed)):
+        raise HTTPException(status_code=400, detail="query is invalid")
+    title, year = split_search_string(search_string)
+    # the same scoring find_best_match picks with, so mis-ranks can be reported
+    candidates = sorted(
+        (
+            {
+                "tvdb_id": x.get("tvdb_id"),
+                "name": x.get("name"),
+                "type": x.get("type"),
+                "year": x.get("year"),
+                "image_url": x.get("image_url"),
+                "score": match_score(x, title, year),
+            }
+            for x in await search_tvdb(search_string)
+        ),
+        key=lambda x: x["score"],
+        reverse=True,
+    )
+    return candidates[: max(1, min(limit, MAX_SEARCH_RESULTS))]
+
+
+@app.get("/parse", response_model=dict[str, str | list[str]])
+async def parse(query: str):
+    # everything anitopy found, for debugging odd matches
+    return parse_query(query)
+
+
+@app.get("/search-terms")
+async def search_terms(query: str):
+    parsed = lookup_query(query)
+    search_string = get_search_string(parsed)
+    title = parsed.get("anime_title")
+    return {
+        "tvdb": [search_string] if search_string else [],
+        "subsplease": get_subsplease_slugs(title) if title else [],
+    }
+
+
+@cache(expire=86400)
+async def get_poster_by_id(tvdb_id: int) -> str | None:
+    series = await tvdb.get_series_extended(tvdb_id)
+    if not series:
+        return None
+    return await get_series_poster(
+        {"tvdb_id": tvdb_id, "type": "series", "image_url": series.get("image")}
+    )
+
+
+@app.post("/posters/by-ids")
+async def posters_by_ids(request: Request, ids: Annotated[list[int], Body()]):
+    if len(ids) > MAX_BATCH_SIZE:
+        raise HTTPException(
+            status_code=400, detail=f"at most {MAX_BATCH_SIZE} ids per request"
+        )
+    if retry_after := charge_rate_limit(request, max(len(ids) - 1, 0)):
+        return rate_limit_response(retry_after)
+
+    async def resolve(tvdb_id: int) -> dict:
+        async with batch_semaphore:
+            try:
+                poster = await get_poster_by_id(tvdb_id)
+            except TVDBError as e:
+                logger.warning(f"Poster lookup failed for {tvdb_id}: {e}")
+                poster = None
+        return {"id": tvdb_id, "poster_url": upgrade_url(poster) if poster else None}
+
+    return await asyncio.gather(*(resolve(x) for x in ids))
+
+
+# fanart.tv tags art with iso 639-1 codes, tvdb and DEFAULT_ART_LANG use 639-2
+FANART_TV_LANGUAGES = {
+    "ar": "ara",
+    "bg": "bul",
+    "ca": "cat",
+    "cs": "ces",
+    "da": "dan",
+    "de": "deu",
+    "el": "ell",
+    "en": "eng",
+    "es": "spa",
+    "et": "est",
+    "fa": "fas",
+    "fi": "fin",
+    "fr": "fra",
+    "he": "heb",
+    "hi": "hin",
+    "hr": "hrv",
+    "hu": "hun",
+    "id": "ind",
+    "it": "ita",
+    "ja": "jpn",
+    "ko": "kor",
+    "lt": "lit",
+    "lv": "lav",
+    "ms": "msa",
+    "nl": "nld",
+    "no": "nor",
+    "pl": "pol",
+    "pt": "por",
+    "ro": "ron",
+    "ru": "rus",
+    "sk": "slk",
+    "sl": "slv",
+    "sr": "srp",
+    "sv": "swe",
+    "th": "tha",
+    "tr": "tur",
+    "uk": "ukr",
+    "vi": "vie",
+    "zh": "zho",
+}
+
+
+@cache(expire=86400)
+async def get_fanarttv_backgrounds(tvdb_id: int) -> list[dict]:
+    response = await http_client.get(
+        f"https://webservice.fanart.tv/v3/tv/{tvdb_id}",
+        params={"api_key": FANART_TV_API_KEY},
+    )
+    if response.status_code != 200:
+        return []
+    backgrounds = response.json().get("showbackground") or []
+    # shaped like tvdb artworks so both pools can be mixed
+    artworks = []
+    for background in backgrounds:
+        if not (url := background.get("url")):
+            continue
+        # "00" marks art without a language
+        language = background.get("lang")
+        if language in ("", "00"):
+            language = None
+        else:
+            language = FANART_TV_LANGUAGES.get(language, language)
+        artworks.append({"image": url, "language": language})
+    return artworks
+
+
+async def get_fanart(
+    parsed: dict[str, str], lang: str | None = None
+) -> list[dict] | None:
+    series = await resolve_match(parsed)
+    if not series or not (series_id := series.get("tvdb_id")):
+        return None
+    return await get_series_fanart(series_id, series.get("type"), lang)
+
+
+# keyed by id, so differently named files for one show share the work
+@cache(expire=86400)
+async def get_series_fanart(
+    series_id: int, type: str | None, lang: str | None = None
+) -> list[dict] | None:
+    artworks = await get_artworks(
+        series_id, type, artwork.SERIES_BACKGROUND, artwork.MOVIE_BACKGROUND
+    )
+    if FANART_TV_API_KEY and type == "series":
+        try:
+            artworks = artworks + await get_fanarttv_backgrounds(series_id)
+        except httpx.HTTPError as e:
+            logger.warning(f"Fanart.tv lookup failed for {series_id}: {e}")
+    return prefer_language(artworks, lang) or None
+
+
+@app.get("/fanart")
+async def fanart(
+    query: str,
+    poster_fallback: bool = False,
+    lang: str = DEFAULT_ART_LANG,
+    format: ResponseFormat = "redirect",
+    proxy: bool = False,
+    count: int | None = None,
+    seed: int | None = None,
+):
+    if count is not None and format != "json":
+        raise HTTPException(status_code=400, detail="count requires format=json")
+    parsed = lookup_query(query)
+    fanart = await get_fanart(parsed, lang or None)
+    # a list for gallery-style choosers, in the order tvdb returns them
+    if count is not None and fanart:
+        images = fanart[: max(1, min(count, MAX_FANART_COUNT))]
+        return JSONResponse([upgrade_url(x["image"]) for x in images])
+    # a seed makes the pick reproducible, e.g. for screenshots
+    rng = random.Random(seed) if seed is not None else random
+    if fanart and (image := rng.choice(fanart).get("image")):
+        response = await art_response(image, format, proxy)
+        # the pool is cached, the pick is re-rolled, so clients shouldn't pin one
+        if seed is None:
+            response.headers["Cache-Control"] = "no-cache"
+        return response
+    # opt-in, since a poster isn't really fanart
+    options = PosterOptions(lang=lang or None)
+    if poster_fallback and (poster := await get_tvdb_poster(parsed, options)):
+        response = await art_response(poster, format, proxy)
+        response.headers["X-Fanart-Fallback"] = "poster"
+        return response
+    raise HTTPException(status_code=404, detail="fanart not found")
+
+
+@cache(expire=86400)
+async def get_episode_image(tvdb_id: int, season: int, episode: int) -> str | None:
+    episodes = await tvdb.get_series_episodes(tvdb_id, season, episode)
+    match = next((x for x in episodes if x.get("number") == episode), None)
+    return match.get("image") if match else None
+
+
+@app.get("/episode-art")
+async def episode_art(
+    query: str, format: ResponseFormat = "redirect", proxy: bool = False
+):
+    parsed = lookup_query(query)
+    if (episode := first_number(parsed.get("episode_number"))) is None:
+        raise HTTPException(status_code=400, detail="query has no episode number")
+    series = await resolve_match(parsed)
+    if not series or series.get("type") != "series":
+        raise HTTPException(status_code=404, detail="episode art not found")
+    season = first_number(parsed.get("anime_season")) or 1
+    image = await get_episode_image(series.get("tvdb_id"), season, episode)
+    if image:
+        return await art_response(image, format, proxy)
+    raise HTTPException(status_code=404, detail="episode art not found")
+
+
+@cache(expire=86400)
+async def get_character_image(tvdb_id: int, name: str) -> str | None:
+    characters = await tvdb.get_series_characters(tvdb_id)
+    target = normalize_title(name)
+
+    def score(character: dict) -> float:
+        candidate = normalize_title(character.get("name") or "")
+        # "Lelouch" should find "Lelouch Lamperouge"
+        if target and target in candidate:
+            return 1.0
+        return SequenceMatcher(None, target, candidate).ratio()
+
+    scored = [(score(x), x) for x in characters if x.get("image")]
+    best = max(scored, key=lambda x: x[0], default=None)
+    return best[1]["image"] if best and best[0] >= 0.8 else None
+
+
+@app.get("/character-art")
+async def character_art(
+    query: str, name: str, format: ResponseFormat = "redirect", proxy: bool = False
+):
+    parsed = lookup_query(query)
+    series = await resolve_match(parsed)
+    if not series or not (series_id := series.get("tvdb_id")):
+        raise HTTPException(status_code=404, detail="series not found")
+    if series.get("type") != "series":
+        raise HTTPException(status_code=404, detail="character not found")
+    image = await get_character_image(series_id, name)
+    if image:
+        return await art_response(image, format, proxy)
+    raise HTTPException(status_code=404, detail="character not found")
+
+
+@app.get("/banner")
+async def banner(
+    query: str, format: ResponseFormat = "redirect", proxy: bool = False
+):
+    parsed = lookup_query(query)
+    banners = await get_match_artworks(
+        parsed, artwork.SERIES_BANNER, artwork.MOVIE_BANNER
+    )
+    if not banners:
+        raise HTTPException(status_code=404, detail="banner not found")
+    return await art_response(banners[0]["image"], format, proxy)
+
+
+@app.get("/clearlogo")
+@app.get("/logo")
+async def clearlogo(
+    query: str,
+    lang: str = DEFAULT_ART_LANG,
+    format: ResponseFormat = "redirect",
+    proxy: bool = False,
+):
+    parsed = lookup_query(query)
+    logos = await get_match_artworks(
+        parsed, artwork.SERIES_CLEARLOGO, artwork.MOVIE_CLEARLOGO
+    )
+    # a poster isn't transparent, so there's deliberately no fallback to one
+    if not (logos := prefer_language(logos, lang or None)):
+        raise HTTPException(status_code=404, detail="clearlogo not found")
+    return await art_response(logos[0]["image"], format, proxy)
+
+
 @cache(expire=86400)
-async def get_fanart(parsed: dict[str, str]) -> list[dict] | None:
+async def get_series_art_manifest(parsed: dict[str, str]) -> dict | None:
     search_string = get_search_string(parsed)
     if not search_string:
         return None
     series = await find_best_match(search_string)
     if (
         not series
+        or series.get("type") != "series"
         or not (series_id := series.get("tvdb_id"))
-        or not (series_type := series.get("type"))
     ):
         return None
-    if series_type == "series":
-        artworks = await tvdb.get_series_artworks(series_id, type=3)
-        return artworks.get("artworks") if artworks else None
-    elif series_type == "movie":
-        movie = await tvdb.get_movie_extended(series_id)
-        if not movie or not (artworks := movie.get("artworks")):
-            return None
-        filtered = list(filter(lambda x: x.get("type") == 15, artworks))
-        return filtered if filtered else None
-    else:
+    extended = await tvdb.get_series_extended(series_id)
+    if not extended:
         return None
+    artworks = extended.get("artworks") or []
 
+    def first_image(type: int) -> str | None:
+        matching = (x.get("image") for x in artworks if x.get("type") == type)
+        return next((x for x in matching if x), None)
 
-@app.get("/fanart")
-async def fanart(query: str):
-    if not (parsed := anitopy.parse(query)):
+    # only one ordering, otherwise dvd/absolute orders repeat season numbers
+    seasons = sorted(
+        (
+            x
+            for x in extended.get("seasons") or []
+            if x.get("type", {}).get("type") == SEASON_TYPE
+        ),
+        key=lambda x: x.get("number", 0),
+    )
+
+    # the extended record usually carries each season's image already, only the
+    # seasons without one cost a request of their own
+    async def season_artwork(season: dict) -> str | None:
+        if image := season.get("image"):
+            return image
+        async with batch_semaphore:
+            return await get_season_artwork(season.get("id"))
+
+    season_images = await asyncio.gather(
+        *(season_artwork(x) for x in seasons)
+    )
+    return {
+        "tvdb_id": series_id,
+        "name": extended.get("name"),
+        "poster": extended.get("image") or series.get("image_url"),
+        "fanart": first_image(artwork.SERIES_BACKGROUND),
+        "banner": first_image(artwork.SERIES_BANNER),
+        "seasons": [
+            {"number": season.get("number"), "poster": image}
+            for season, image in zip(seasons, season_images)
+        ],
+    }
+
+
+@app.get("/series-art-manifest")
+async def series_art_manifest(query: str):
+    parsed = lookup_query(query)
+    if not parsed.get("anime_title"):
         raise HTTPException(status_code=400, detail="query is invalid")
-    fanart = await get_fanart(parsed)
-    if not fanart or not (image := random.choice(fanart).get("image")):
-        raise HTTPException(status_code=404, detail="fanart not found")
-    return RedirectResponse(url=image, status_code=302)
+    manifest = await get_series_art_manifest(parsed)
+    if not manifest:
+        raise HTTPException(status_code=404, detail="series not found")
+    return manifest
 
 
-@cache(expire=86400)
-async def get_torrent_art(url: str):
-    async with httpx.AsyncClient(http2=True) as client:
-        response = await client.get(url, follow_redirects=True)
-        if response.status_code == 200:
-            description = html.fromstring(response.text).xpath(
-                "string(//div[@id='torrent-description'])"
-            )
-            if not description:
-                return None
-            pattern = r"https?://[^\s]+?\.(?:jpg|jpeg|png|gif)"
-            match = re.search(pattern, description)
-            return match.group(0) if match else None
+async def get_torrent_page(url: str) -> html.HtmlElement | None:
+    response = await http_client.get(url, follow_redirects=True)
+    if response.status_code != 200:
+        return None
+    # after redirects, so relative links on the page resolve against where it lives
+    return html.fromstring(response.text, base_url=str(response.url))
+
+
+def get_torrent_description(page: html.HtmlElement) -> str | None:
+    return page.xpath("string(//div[@id='torrent-description'])") or None
+
+
+def get_meta_image(page: html.HtmlElement) -> str | None:
+    # some uploads only set the art as the page preview
+    for selector in ("//meta[@property='og:image']", "//meta[@name='twitter:image']"):
+        if content := page.xpath(f"string({selector}/@content)"):
+            return urljoin(page.base_url or "", content)
     return None
 
 
+def find_image_urls(text: str) -> list[str]:
+    pattern = r"https?://[^\s]+?\.(?:jpg|jpeg|png|gif)"
+    return list(dict.fromkeys(re.findall(pattern, text)))
+
+
+async def pick_torrent_image(images: list[str]) -> str | None:
+    if len(images) <= 1:
+        return images[0] if images else None
+    # descriptions mix the cover with banners and emoji sprites
+    for image in images:
+        filename = urlparse(image).path.rsplit("/", 1)[-1].lower()
+        if "cover" in filename or "poster" in filename:
+            return image
+
+    async def size(image: str) -> int:
+        try:
+            response = await http_client.head(image)
+            return int(response.headers.get("content-length", 0))
+        except (httpx.HTTPError, ValueError):
+            return 0
+
+    public = await asyncio.gather(*(is_public_https_url(x) for x in images))
+    probed = [x for x, ok in zip(images, public) if ok][:MAX_IMAGE_PROBES]
+    sizes = await asyncio.gather(*(size(x) for x in probed))
+    if any(sizes):
+        return max(zip(sizes, probed))[1]
+    return images[0]
+
+
+@cache(expire=TORRENT_CACHE_TTL_SECS)
+async def get_torrent_art(url: str):
+    if (page := await get_torrent_page(url)) is None:
+        return None
+    description = get_torrent_description(page) or ""
+    if image := await pick_torrent_image(find_image_urls(description)):
+        return image
+    if image := get_meta_image(page):
+        return image
+    # some uploads link to a page holding the art, follow one hop and no further
+    host = urlparse(url).hostname
+    links = re.findall(r"https?://[^\s)\]>\"]+", description)
+    if not (link := next((x for x in links if urlparse(x).hostname == host), None)):
+        return None
+    linked_page = await get_torrent_page(link)
+    if linked_page is None or not (linked := get_torrent_description(linked_page)):
+        return None
+    return await pick_torrent_image(find_image_urls(linked))
+
+
+async def resolves_publicly(host: str) -> bool:
+    # an allowed name could still point at an internal address
+    try:
+        infos = await asyncio.get_running_loop().getaddrinfo(
+            host, 443, type=socket.SOCK_STREAM
+        )
+    except OSError:
+        return False
+    return bool(infos) and all(
+        # link-local v6 answers carry a %scope suffix ip_address won't take
+        ipaddress.ip_address(info[4][0].split("%")[0]).is_global
+        for info in infos
+    )
+
+
+async def is_public_https_url(url: str) -> bool:
+    parsed = urlparse(url)
+    if parsed.scheme != "https" or not (host := parsed.hostname):
+        return False
+    # resolving catches internal names and odd literals like 127.1 or 2130706433
+    return await resolves_publicly(host)
+
+
+def is_allowed_image_host(url: str) -> bool:
+    if "*" in IMAGE_ALLOWED_HOSTS:
+        return True
+    host = (urlparse(url).hostname or "").lower()
+    return any(host == x or host.endswith(f".{x}") for x in IMAGE_ALLOWED_HOSTS)
+
+
+async def is_allowed_torrent_url(url: str) -> bool:
+    # the exact host, so nyaa.si.evil.com doesn't pass for nyaa.si
+    parsed_url = urlparse(url)
+    return (
+        parsed_url.scheme == "https"
+        and parsed_url.hostname in TORRENT_ALLOWED_HOSTS
+        and await resolves_publicly(parsed_url.hostname)
+    )
+
+
 @app.get("/torrent-art")
-async def torrent_art(url: str):
-    if not url.startswith(("https://nyaa.si", "https://sukebei.nyaa.si/")):
+async def torrent_art(
+    url: str, format: ResponseFormat = "redirect", proxy: bool = False
+):
+    if not await is_allowed_torrent_url(url):
         raise HTTPException(status_code=400, detail="invalid url")
     image = await get_torrent_art(url)
     if image:
-        return RedirectResponse(url=image, status_code=302)
+        return await art_response(image, format, proxy)
     raise HTTPException(status_code=404, detail="art not found")
 
 
+@cache(expire=86400)
+async def get_valid_image(url: str) -> str | None:
+    try:
+        # redirects aren't followed, they could point somewhere internal
+        response = await http_client.head(url)
+    except httpx.HTTPError:
+        return None
+    content_type = response.headers.get("content-type", "")
+    if response.status_code == 200 and content_type.startswith("image/"):
+        return url
+    return None
+
+
+@app.get("/validate-image")
+async def validate_image(url: str):
+    if not is_allowed_image_host(url) or not await is_public_https_url(url):
+        raise HTTPException(status_code=400, detail="invalid url")
+    image = await get_valid_image(url)
+    if image:
+        return await art_response(image)
+    raise HTTPException(status_code=404, detail="image not found")
+
+
+@app.get("/metrics")
+async def metrics():
+    return Response(generate_latest(), media_type=CONTENT_TYPE_LATEST)
+
+
+@app.get("/cache-stats")
+async def get_cache_stats():
+    return cache_stats()
+
+
+def require_admin(authorization: str | None) -> None:
+    # purging is never open to everyone, even when no token was configured
+    if not ADMIN_TOKEN:
+        raise HTTPException(status_code=403, detail="admin endpoints are disabled")
+    if not secrets.compare_digest(authorization or "", f"Bearer {ADMIN_TOKEN}"):
+        raise HTTPException(status_code=401, detail="unauthorized")
+
+
+async def invalidate_query(query: str) -> int:
+    if query.startswith("https://"):
+        return int(await invalidate(get_torrent_art, query))
+    parsed = lookup_query(query)
+    title = parsed.get("anime_title", "")
+    series_parsed = {k: v for k, v in parsed.items() if k not in SEASON_KEYS}
+    options = PosterOptions(lang=DEFAULT_ART_LANG or None)
+    search_string = get_search_string(parsed)
+    # art is cached by tvdb id, so find it through the matches before dropping them
+    by_id = []
+    if search_string and (series := await find_best_match(search_string)):
+        series_id, type = series.get("tvdb_id"), series.get("type")
+        by_id += [
+            invalidate(
+                get_artworks,
+                series_id,
+                type,
+                artwork.SERIES_BACKGROUND,
+                artwork.MOVIE_BACKGROUND,
+            ),
+            invalidate(
+                get_artworks,
+                series_id,
+                type,
+                artwork.SERIES_POSTER,
+                artwork.MOVIE_POSTER,
+            ),
+            invalidate(get_series_fanart, series_id, type, options.lang),
+            invalidate(get_absolute_seasons, series_id),
+        ]
+        try:
+            season_number = await get_poster_season(series, parsed)
+        except (TVDBError, ValueError):
+            season_number = None
+        if season_number:
+            by_id.append(
+                invalidate(
+                    get_season_image,
+                    series_id,
+                    season_number,
+                    options.lang,
+                    options.season_type,
+                )
+            )
+    movie = search_string and await find_best_match(search_string, "movie")
+    if movie and (movie_id := movie.get("tvdb_id")):
+        by_id.append(
+            invalidate(
+                get_artworks,
+                movie_id,
+                "movie",
+                artwork.SERIES_POSTER,
+                artwork.MOVIE_POSTER,
+            )
+        )
+    # the variants the endpoints resolve with their default parameters
+    cleared = await asyncio.gather(
+        *by_id,
+        invalidate(search_tvdb, search_string),
+        invalidate(find_best_match, search_string),
+        invalidate(find_best_match, search_string, "movie"),
+        invalidate(get_tvdb_poster, parsed, options),
+        invalidate(get_tvdb_poster, series_parsed, options),
+        invalidate(get_subsplease_poster, title),
+        invalidate(get_kitsu_poster, title),
+        invalidate(get_tmdb_poster, title, parsed.get("anime_year")),
+        invalidate(get_series_art_manifest, parsed),
+    )
+    return sum(cleared)
+
+
+@app.delete("/cache")
+async def purge_cache(
+    query: str | None = None,
+    authorization: Annotated[str | None, Header()] = None,
+):
+    require_admin(authorization)
+    cleared = await invalidate_query(query) if query else await invalidate_all()
+    return {"cleared": cleared}
+
+
 @app.get("/healthcheck")
 @app.head("/healthcheck")
 async def healthcheck():
     return {"status": "ok"}
+
+
+@app.get("/readyz")
+async def readyz():
+    # only log in again when the token is missing or expired, probes run often
+    expired = not tvdb.token_expires or tvdb.token_expires < time.time()
+    try:
+        token = await tvdb.relogin(tvdb.token) if expired else tvdb.token
+    except httpx.HTTPError as e:
+        logger.warning(f"Readiness check could not reach TVDB: {e}")
+        return error_response(503, "tvdb unreachable", source="tvdb")
+    if not token:
+        return error_response(503, "tvdb login failed", source="tvdb")
+    return {"status": "ok"}
+
+
+POSTER_SOURCE_PROBES = {
+    "tmdb": "https://api.themoviedb.org/3",
+    "subsplease": "https://subsplease.org",
+    "kitsu": "https://kitsu.io/api/edge",
+}
+# only what this deployment actually calls, tvdb backs every match regardless
+SOURCE_PROBES = {
+    "tvdb": tvdb.api_base,
+    **{x: POSTER_SOURCE_PROBES[x] for x in POSTER_SOURCES if x in POSTER_SOURCE_PROBES},
+    "nyaa": "https://nyaa.si",
+}
+if FANART_TV_API_KEY:
+    SOURCE_PROBES["fanarttv"] = "https://webservice.fanart.tv"
+
+
+# cached briefly so the endpoint can't be used to hammer upstreams
+@cache(expire=60)
+async def probe_sources() -> dict[str, dict]:
+    async def probe(url: str) -> dict:
+        start = time.monotonic()
+        try:
+            response = await http_client.head(url, follow_redirects=True)
+            up = response.status_code < 500
+        except httpx.HTTPError:
+            up = False
+        return {"up": up, "latency_ms": round((time.monotonic() - start) * 1000)}
+
+    results = await asyncio.gather(*(probe(url) for url in SOURCE_PROBES.values()))
+    return dict(zip(SOURCE_PROBES, results))
+
+
+@app.get("/healthz/sources")
+async def healthz_sources():
+    return await probe_sources()
@@ -0,0 +1,14 @@
+#!/usr/bin/env python3
+# TVDB artwork type ids, as listed by GET /artwork/types
+SERIES_BANNER = 1
+SERIES_POSTER = 2
+SERIES_BACKGROUND = 3
+SEASON_BANNER = 6
+SEASON_POSTER = 7
+MOVIE_POSTER = 14
+MOVIE_BACKGROUND = 15
+MOVIE_BANNER = 16
+SERIES_CLEARART = 22
+SERIES_CLEARLOGO = 23
+MOVIE_CLEARART = 24
+MOVIE_CLEARLOGO = 25
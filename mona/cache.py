@@ -0,0 +1,133 @@
+#!/usr/bin/env python3
+import asyncio
+import contextvars
+import functools
+import hashlib
+import json
+import os
+import time
+from collections import Counter
+from collections.abc import Awaitable, Callable
+from typing import Any, ParamSpec, TypeVar
+
+from fastapi_cache import FastAPICache
+from fastapi_cache.backends.redis import RedisBackend
+from loguru import logger
+
+NEGATIVE_CACHE_TTL_SECS = int(os.environ.get("NEGATIVE_CACHE_TTL_SECS", "600"))
+STALE_WHILE_REVALIDATE = (
+    os.environ.get("STALE_WHILE_REVALIDATE", "false").lower() in ("1", "true", "yes")
+)
+CACHE_SOFT_TTL_SECS = int(os.environ.get("CACHE_SOFT_TTL_SECS", "3600"))
+
+P = ParamSpec("P")
+R = TypeVar("R")
+
+# keys with a background refresh in flight, and the tasks doing it
+refreshing: set[str] = set()
+background_tasks: set[asyncio.Task] = set()
+# set inside a background refresh, so the cached calls it makes re-resolve too
+# instead of handing back the stale values it is meant to replace
+bypass: contextvars.ContextVar[bool] = contextvars.ContextVar("bypass", default=False)
+# hit/miss counts per cached function
+stats: dict[str, dict[str, int]] = {}
+
+
+def build_key(func: Callable, args: tuple, kwargs: dict) -> str:
+    digest = hashlib.md5(repr((args, sorted(kwargs.items()))).encode()).hexdigest()
+    return f"{FastAPICache.get_prefix()}:{func.__module__}:{func.__name__}:{digest}"
+
+
+async def store(key: str, result: Any, expire: int) -> None:
+    # a miss today may resolve once upstream adds the title, and an empty
+    # artwork list is as much a miss as None
+    ttl = expire if result else min(expire, NEGATIVE_CACHE_TTL_SECS)
+    entry = {"time": time.time(), "value": result}
+    await FastAPICache.get_backend().set(key, json.dumps(entry).encode(), ttl)
+
+
+async def refresh(key: str, func: Callable, args: tuple, kwargs: dict, expire: int):
+    bypass.set(True)
+    try:
+        await store(key, await func(*args, **kwargs), expire)
+    except Exception as e:
+        logger.warning(f"Background refresh of {func.__name__} failed: {e}")
+    finally:
+        refreshing.discard(key)
+
+
+def cache(
+    expire: int,
+) -> Callable[[Callable[P, Awaitable[R]]], Callable[P, Awaitable[R]]]:
+    def decorator(func: Callable[P, Awaitable[R]]) -> Callable[P, Awaitable[R]]:
+        counts = stats.setdefault(func.__name__, {"hits": 0, "misses": 0})
+
+        @functools.wraps(func)
+        async def wrapper(*args: P.args, **kwargs: P.kwargs) -> R:
+            key = build_key(func, args, kwargs)
+            if not bypass.get() and (
+                cached := await FastAPICache.get_backend().get(key)
+            ) is not None:
+                counts["hits"] += 1
+                entry = json.loads(cached)
+                age = time.time() - entry["time"]
+                # serve the stale value now, re-resolve it in the background
+                if (
+                    STALE_WHILE_REVALIDATE
+                    and age > CACHE_SOFT_TTL_SECS
+                    and entry["value"]
+                    and key not in refreshing
+                ):
+                    refreshing.add(key)
+                    task = asyncio.create_task(
+                        refresh(key, func, args, kwargs, expire)
+                    )
+                    background_tasks.add(task)
+                    task.add_done_callback(background_tasks.discard)
+                return entry["value"]
+            counts["misses"] += 1
+            result = await func(*args, **kwargs)
+            await store(key, result, expire)
+            return result
+
+        return wrapper
+
+    return decorator
+
+
+def cache_stats() -> dict[str, dict[str, int]]:
+    # only the in-memory backend can be counted without scanning a remote store
+    entries = Counter(
+        key.rsplit(":", 2)[1]
+        for key in getattr(FastAPICache.get_backend(), "_store", {})
+    )
+    return {
+        name: {**counts, "entries": entries.get(name, 0)}
+        for name, counts in stats.items()
+    }
+
+
+async def invalidate(func: Callable, *args: Any, **kwargs: Any) -> bool:
+    key = build_key(func, args, kwargs)
+    backend = FastAPICache.get_backend()
+    if await backend.get(key) is None:
+        return False
+    await backend.clear(key=key)
+    return True
+
+
+async def invalidate_all() -> int:
+    backend = FastAPICache.get_backend()
+    if not isinstance(backend, RedisBackend):
+        return await backend.clear(namespace=FastAPICache.get_prefix())
+    # KEYS would block a shared redis, and clear() doesn't report what it removed
+    cleared = 0
+    batch: list[bytes] = []
+    async for key in backend.redis.scan_iter(match=f"{FastAPICache.get_prefix()}:*"):
+        batch.append(key)
+        if len(batch) >= 500:
+            cleared += await backend.redis.delete(*batch)
+            batch.clear()
+    if batch:
+        cleared += await backend.redis.delete(*batch)
+    return cleared
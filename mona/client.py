@@ -0,0 +1,30 @@
+#!/usr/bin/env python3
+import os
+from urllib.parse import urlparse
+
+import httpx
+
+HTTP_TIMEOUT_SECS = float(os.environ.get("HTTP_TIMEOUT_SECS", "15"))
+# a hung upstream shouldn't stall a handler, connecting gets a shorter leash
+HTTP_TIMEOUT = httpx.Timeout(HTTP_TIMEOUT_SECS, connect=min(HTTP_TIMEOUT_SECS, 5))
+# without it httpx still honors HTTPS_PROXY/HTTP_PROXY from the environment
+MONA_PROXY = os.environ.get("MONA_PROXY") or None
+
+# one pool for every upstream, so timeouts and tls settings live in one place
+http_client = httpx.AsyncClient(http2=True, timeout=HTTP_TIMEOUT, proxy=MONA_PROXY)
+
+
+def describe_proxy() -> str | None:
+    proxy = (
+        MONA_PROXY
+        or os.environ.get("HTTPS_PROXY")
+        or os.environ.get("https_proxy")
+        or os.environ.get("HTTP_PROXY")
+        or os.environ.get("http_proxy")
+    )
+    if not proxy:
+        return None
+    # only scheme, host and port, the userinfo may hold credentials
+    parsed = urlparse(proxy)
+    port = f":{parsed.port}" if parsed.port else ""
+    return f"{parsed.scheme}://{parsed.hostname}{port}"
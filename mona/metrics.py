@@ -0,0 +1,21 @@
+#!/usr/bin/env python3
+from prometheus_client import Counter, Histogram
+
+REQUESTS = Counter(
+    "mona_requests_total",
+    "Requests handled, by route and status class",
+    ["method", "route", "status"],
+)
+LATENCY = Histogram(
+    "mona_request_duration_seconds",
+    "Request latency, by route",
+    ["method", "route"],
+)
+
+
+def record_request(method: str, route: str, status_code: int, latency: float):
+    status = f"{status_code // 100}xx"
+    REQUESTS.labels(method, route, status).inc()
+    # health probes would swamp the latency buckets
+    if route != "/healthcheck":
+        LATENCY.labels(method, route).observe(latency)
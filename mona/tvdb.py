@@ -1,56 +1,191 @@
 #!/usr/bin/env python3
 import asyncio
+import base64
+import json
 import os
+import time
+from typing import Any, TypedDict
+from urllib.parse import quote
 
 import httpx
 from loguru import logger
 
+from mona.client import http_client
+
+
+RATE_LIMIT_RETRIES = 3
+# tvdb pages episode listings 500 at a time, this covers even the longest shows
+MAX_EPISODE_PAGES = 20
+
+
+class TVDBError(Exception):
+    pass
+
+
+class TVDBNetworkError(TVDBError):
+    pass
+
+
+class TVDBAuthError(TVDBError):
+    pass
+
+
+class TVDBRateLimitError(TVDBError):
+    pass
+
+
+class TVDBDecodeError(TVDBError):
+    pass
+
+
+class SearchResult(TypedDict, total=False):
+    # a string from search, an int from the remote id lookup
+    tvdb_id: str | int
+    name: str
+    slug: str
+    translations: dict[str, str]
+    aliases: list[str]
+    primary_language: str
+    type: str
+    image_url: str
+    year: str
+    first_air_time: str
+
+
+def search_result(raw: dict) -> SearchResult:
+    result = SearchResult(
+        **{k: v for k, v in raw.items() if k in SearchResult.__annotations__}
+    )
+    # the id shows up as tvdb_id, or prefixed with the type as id/objectID
+    if not result.get("tvdb_id"):
+        prefixed = raw.get("id") or raw.get("objectID") or ""
+        if tvdb_id := str(prefixed).rpartition("-")[2]:
+            result["tvdb_id"] = tvdb_id
+    image = raw.get("image") or raw.get("thumbnail")
+    if not result.get("image_url") and image:
+        result["image_url"] = image
+    return result
+
+
+def retry_after(response: httpx.Response) -> float | None:
+    try:
+        return float(response.headers.get("retry-after", ""))
+    except ValueError:
+        return None
+
+
+def token_expiry(token: str) -> float | None:
+    # tvdb tokens are JWTs, the exp claim says when they stop working
+    try:
+        payload = token.split(".")[1]
+        payload += "=" * (-len(payload) % 4)
+        return float(json.loads(base64.urlsafe_b64decode(payload))["exp"])
+    except (IndexError, KeyError, TypeError, ValueError):
+        return None
+
 
 class TVDB:
-    def __init__(self, apikey: str, pin: str = "hello world"):
+    def __init__(
+        self,
+        apikey: str,
+        pin: str | None = None,
+        client: httpx.AsyncClient | None = None,
+    ):
         self.token: str | None = None
         self.apikey: str = apikey
-        self.pin: str = pin
+        # project keys accept any pin, subscriber keys need the real one
+        self.pin: str = pin or "hello world"
         self.api_base: str = "https://api4.thetvdb.com/v4"
+        self.token_expires: float | None = None
+        self.refresh: asyncio.TimerHandle | None = None
+        self.login_lock = asyncio.Lock()
+        self.client = client or http_client
+
+    def schedule_refresh(self, delay: float) -> None:
+        if self.refresh:
+            self.refresh.cancel()
+        self.refresh = asyncio.get_event_loop().call_later(
+            delay, lambda: asyncio.create_task(self.login())
+        )
 
     async def login(self) -> str | None:
-        # refresh token every hour
-        asyncio.get_event_loop().call_later(
-            3600, lambda: asyncio.create_task(self.login())
+        # try again in an hour if this login fails
+        self.schedule_refresh(3600)
+        response = await self.client.post(
+            f"{self.api_base}/login",
+            json={"apikey": self.apikey, "pin": self.pin},
         )
-        async with httpx.AsyncClient(http2=True) as client:
-            response = await client.post(
-                f"{self.api_base}/login",
-                json={"apikey": self.apikey, "pin": self.pin},
-            )
-            if response.status_code == 200:
-                self.token = response.json().get("data", {}).get("token")
-                logger.info("TVDB token refreshed!")
-            else:
-                logger.error("TVDB token refresh failed!")
-        return self.token
-
-    async def search(self, query: str) -> list[dict]:
-        async with httpx.AsyncClient(http2=True) as client:
-            response = await client.get(
-                f"{self.api_base}/search",
-                params={"query": query},
-                headers={"Authorization": f"Bearer {self.token}"},
-            )
-            if response.status_code == 200:
-                return response.json().get("data", [])
-        return []
+        if response.status_code == 200:
+            self.token = response.json().get("data", {}).get("token")
+            expires = token_expiry(self.token) if self.token else None
+            if expires:
+                # refresh an hour before the token actually expires
+                self.schedule_refresh(max(60, expires - time.time() - 3600))
+            elif self.token:
+                # no readable exp claim, the hourly refresh scheduled above stands
+                expires = time.time() + 3600
+            self.token_expires = expires
+            logger.info("TVDB token refreshed!")
+            return self.token
+        logger.error("TVDB token refresh failed!")
+        return None
 
-    async def get_series_extended(self, series_id: int) -> dict | None:
-        async with httpx.AsyncClient(http2=True) as client:
-            response = await client.get(
-                f"{self.api_base}/series/{series_id}/extended",
+    async def _send(self, path: str, params: dict | None) -> httpx.Response:
+        for attempt in range(RATE_LIMIT_RETRIES + 1):
+            response = await self.client.get(
+                f"{self.api_base}{path}",
+                params=params,
                 headers={"Authorization": f"Bearer {self.token}"},
             )
+            if response.status_code != 429:
+                return response
+            if attempt < RATE_LIMIT_RETRIES:
+                delay = min(retry_after(response) or 2**attempt, 10)
+                logger.warning(f"TVDB rate limited, retrying in {delay}s")
+                await asyncio.sleep(delay)
+        raise TVDBRateLimitError("tvdb rate limit exceeded")
+
+    async def relogin(self, rejected_token: str | None) -> str | None:
+        async with self.login_lock:
+            # a concurrent request may have refreshed it while we waited
+            if self.token != rejected_token:
+                return self.token
+            return await self.login()
+
+    async def _get(self, path: str, params: dict | None = None) -> Any:
+        body = await self._get_body(path, params)
+        return body.get("data") if body else None
+
+    async def _get_body(self, path: str, params: dict | None = None) -> dict | None:
+        # the whole response, for when the paging links are needed too
+        try:
+            token = self.token
+            response = await self._send(path, params)
+            if response.status_code == 401:
+                # a token can be revoked before it expires, so log in again once
+                if await self.relogin(token):
+                    response = await self._send(path, params)
+                if response.status_code == 401:
+                    logger.warning("TVDB rejected our credentials, is the key revoked?")
+                    raise TVDBAuthError("tvdb authentication rejected")
             if response.status_code == 200:
-                return response.json().get("data")
+                return response.json()
+        except httpx.HTTPError as e:
+            raise TVDBNetworkError(f"tvdb request failed: {e}") from e
+        except ValueError as e:
+            raise TVDBDecodeError(f"tvdb returned invalid json: {e}") from e
         return None
 
+    async def search(self, query: str) -> list[SearchResult]:
+        results = await self._get("/search", params={"query": query}) or []
+        return [search_result(x) for x in results]
+
+    async def search_by_remote_id(self, remote_id: str) -> list[dict]:
+        return await self._get(f"/search/remoteid/{quote(remote_id, safe='')}") or []
+
+    async def get_series_extended(self, series_id: int) -> dict | None:
+        return await self._get(f"/series/{series_id}/extended")
+
     async def get_series_artworks(
         self, series_id: int, lang: str | None = None, type: int | None = None
     ) -> dict | None:
@@ -59,35 +194,49 @@ class TVDB:
             params["lang"] = lang
         if type:
             params["type"] = type
-        async with httpx.AsyncClient(http2=True) as client:
-            response = await client.get(
-                f"{self.api_base}/series/{series_id}/artworks",
-                params=params,
-                headers={"Authorization": f"Bearer {self.token}"},
-            )
-            if response.status_code == 200:
-                return response.json().get("data")
-        return None
+        return await self._get(f"/series/{series_id}/artworks", params=params)
 
     async def get_movie_extended(self, movie_id: int) -> dict | None:
-        async with httpx.AsyncClient(http2=True) as client:
-            response = await client.get(
-                f"{self.api_base}/movies/{movie_id}/extended",
-                headers={"Authorization": f"Bearer {self.token}"},
-            )
-            if response.status_code == 200:
-                return response.json().get("data")
-        return None
+        return await self._get(f"/movies/{movie_id}/extended")
 
     async def get_season_extended(self, season_id: int) -> dict | None:
-        async with httpx.AsyncClient(http2=True) as client:
-            response = await client.get(
-                f"{self.api_base}/seasons/{season_id}/extended",
-                headers={"Authorization": f"Bearer {self.token}"},
+        return await self._get(f"/seasons/{season_id}/extended")
+
+    async def get_series_characters(self, series_id: int) -> list[dict]:
+        series = await self.get_series_extended(series_id)
+        return (series or {}).get("characters") or []
+
+    async def get_series_episodes(
+        self,
+        series_id: int,
+        season: int | None = None,
+        episode: int | None = None,
+        season_type: str = "default",
+    ) -> list[dict]:
+        params = {"page": 0}
+        if season is not None:
+            params["season"] = season
+        if episode is not None:
+            params["episodeNumber"] = episode
+        data = await self._get(
+            f"/series/{series_id}/episodes/{season_type}", params=params
+        )
+        return (data or {}).get("episodes") or []
+
+    async def get_all_series_episodes(
+        self, series_id: int, season_type: str = "default"
+    ) -> list[dict]:
+        episodes = []
+        for page in range(MAX_EPISODE_PAGES):
+            body = await self._get_body(
+                f"/series/{series_id}/episodes/{season_type}", params={"page": page}
             )
-            if response.status_code == 200:
-                return response.json().get("data")
-        return None
+            if not body:
+                break
+            episodes += (body.get("data") or {}).get("episodes") or []
+            if not (body.get("links") or {}).get("next"):
+                break
+        return episodes
 
 
 async def main():
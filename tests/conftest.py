@@ -0,0 +1,5 @@
+#!/usr/bin/env python3
+import os
+
+# the tvdb login waits for startup, importing the app only needs a key to be set
+os.environ.setdefault("TVDB_API_KEY", "test")
@@ -0,0 +1,27 @@
+#!/usr/bin/env python3
+from fastapi_cache import FastAPICache
+from fastapi_cache.backends.inmemory import InMemoryBackend
+
+from mona.app import get_search_string, get_tvdb_poster, lookup_query, parse_query
+from mona.cache import build_key
+
+
+def test_casing_variants_share_a_lookup():
+    plain = lookup_query("[SubsPlease] Toradora - 01 (1080p).mkv")
+    shouted = lookup_query("[SUBSPLEASE]  TORADORA - 01 (1080p).mkv ")
+    assert plain == shouted
+    assert get_search_string(plain) == "toradora"
+
+
+def test_casing_variants_share_a_cache_key():
+    FastAPICache.init(InMemoryBackend(), prefix="test")
+
+    def key(query: str) -> str:
+        return build_key(get_tvdb_poster, (lookup_query(query),), {})
+
+    assert key("[SubsPlease] Toradora - 01") == key("[subsplease] TORADORA - 01")
+
+
+def test_parse_keeps_raw_casing():
+    parsed = parse_query("[SubsPlease] Toradora - 01 (1080p).mkv")
+    assert parsed["anime_title"] == "Toradora"
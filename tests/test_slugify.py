@@ -0,0 +1,15 @@
+#!/usr/bin/env python3
+from mona.app import get_subsplease_slugs, slugify
+
+
+def test_slugify_macron():
+    # subsplease.org/shows/tokyo-ghoul
+    assert slugify("Tōkyō Ghoul") == "tokyo-ghoul"
+
+
+def test_slugify_accents():
+    assert slugify("Pokémon") == "pokemon"
+
+
+def test_subsplease_slugs_keep_folded_title_first():
+    assert get_subsplease_slugs("Tōkyō Ghoul")[0] == "tokyo-ghoul"
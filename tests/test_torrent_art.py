@@ -0,0 +1,112 @@
+#!/usr/bin/env python3
+import asyncio
+
+import httpx
+import pytest
+from lxml import html
+
+from mona import app
+
+
+@pytest.fixture(autouse=True)
+def public_dns(monkeypatch):
+    # keep the host checks off the network
+    async def resolves_publicly(host: str) -> bool:
+        return True
+
+    monkeypatch.setattr(app, "resolves_publicly", resolves_publicly)
+
+
+def allowed(url: str) -> bool:
+    return asyncio.run(app.is_allowed_torrent_url(url))
+
+
+def test_allows_listed_host():
+    assert allowed("https://nyaa.si/view/1234567")
+
+
+def test_rejects_allowed_host_as_prefix():
+    assert not allowed("https://nyaa.si.evil.com/view/1234567")
+
+
+def test_rejects_allowed_host_as_userinfo():
+    assert not allowed("https://nyaa.si@evil.com/view/1234567")
+
+
+def test_rejects_http_downgrade():
+    assert not allowed("http://nyaa.si/view/1234567")
+
+
+def test_rejects_private_resolution(monkeypatch):
+    async def resolves_publicly(host: str) -> bool:
+        return False
+
+    monkeypatch.setattr(app, "resolves_publicly", resolves_publicly)
+    assert not allowed("https://nyaa.si/view/1234567")
+
+
+OG_IMAGE_PAGE = """
+<html>
+  <head>
+    <meta property="og:image" content="/images/cover.jpg">
+  </head>
+  <body>
+    <div id="torrent-description">No art in here, just the batch notes.</div>
+  </body>
+</html>
+"""
+
+
+def test_og_image_used_when_description_has_none():
+    page = html.fromstring(OG_IMAGE_PAGE, base_url="https://nyaa.si/view/1234567")
+    description = app.get_torrent_description(page)
+    assert app.find_image_urls(description) == []
+    assert app.get_meta_image(page) == "https://nyaa.si/images/cover.jpg"
+
+
+DESCRIPTION = """
+[img]https://i.imgur.com/banner.png[/img]
+[img]https://i.imgur.com/emoji.gif[/img]
+[img]https://i.imgur.com/art.jpg[/img]
+"""
+
+
+@pytest.fixture
+def image_sizes(monkeypatch):
+    # content-length per url, as a HEAD request would report it
+    sizes: dict[str, int] = {}
+
+    async def head(url: str, **kwargs) -> httpx.Response:
+        return httpx.Response(200, headers={"content-length": str(sizes.get(url, 0))})
+
+    async def is_public_https_url(url: str) -> bool:
+        return True
+
+    monkeypatch.setattr(app.http_client, "head", head)
+    monkeypatch.setattr(app, "is_public_https_url", is_public_https_url)
+    return sizes
+
+
+def pick(description: str) -> str | None:
+    return asyncio.run(app.pick_torrent_image(app.find_image_urls(description)))
+
+
+def test_picks_largest_image(image_sizes):
+    image_sizes.update(
+        {
+            "https://i.imgur.com/banner.png": 40_000,
+            "https://i.imgur.com/emoji.gif": 2_000,
+            "https://i.imgur.com/art.jpg": 350_000,
+        }
+    )
+    assert pick(DESCRIPTION) == "https://i.imgur.com/art.jpg"
+
+
+def test_prefers_cover_filename(image_sizes):
+    image_sizes["https://i.imgur.com/banner.png"] = 900_000
+    description = DESCRIPTION + "[img]https://i.imgur.com/cover.jpg[/img]"
+    assert pick(description) == "https://i.imgur.com/cover.jpg"
+
+
+def test_falls_back_to_first_image(image_sizes):
+    assert pick(DESCRIPTION) == "https://i.imgur.com/banner.png"